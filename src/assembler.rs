@@ -0,0 +1,225 @@
+//! Text assembler for the Synacor VM. Mirrors `Machine::disassemble` in reverse: it
+//! parses mnemonics and produces the little-endian word stream `Machine::load` expects,
+//! so a disassembled (and possibly patched) program can be reassembled once the
+//! `address:` prefix that `disassemble` puts on each line is removed; the assembler does
+//! not accept that prefix. Builds under
+//! `no_std` (see `crate::io`'s module doc): uses `BTreeMap` rather than `HashMap` since
+//! `alloc` has no hasher-backed map, and labels are only ever looked up, never iterated
+//! in order, so the choice is free either way.
+
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+#[cfg(not(feature = "std"))]
+use alloc::{collections::BTreeMap, format, string::String, string::ToString, vec::Vec};
+
+fn opcode_of(mnemonic: &str) -> Option<u16> {
+    Some(match mnemonic {
+        "halt" => 0,
+        "set" => 1,
+        "push" => 2,
+        "pop" => 3,
+        "eq" => 4,
+        "gt" => 5,
+        "jmp" => 6,
+        "jt" => 7,
+        "jf" => 8,
+        "add" => 9,
+        "mult" => 10,
+        "mod" => 11,
+        "and" => 12,
+        "or" => 13,
+        "not" => 14,
+        "rmem" => 15,
+        "wmem" => 16,
+        "call" => 17,
+        "ret" => 18,
+        "out" => 19,
+        "in" => 20,
+        "noop" => 21,
+        _ => return None,
+    })
+}
+
+/// The fixed operand count per opcode, matching `match_opcode`/`disassemble`. Each
+/// instruction line must carry exactly this many operands; `assemble` panics on too few
+/// or too many.
+fn arity_of(opcode: u16) -> usize {
+    match opcode {
+        0 | 18 | 21 => 0,                     // halt, ret, noop
+        2 | 3 | 6 | 17 | 19 | 20 => 1,         // push, pop, jmp, call, out, in
+        1 | 7 | 8 | 14 | 15 | 16 => 2,         // set, jt, jf, not, rmem, wmem
+        4 | 5 | 9 | 10 | 11 | 12 | 13 => 3,    // eq, gt, add, mult, mod, and, or
+        _ => unreachable!("opcode_of only returns opcodes handled above"),
+    }
+}
+
+/// Parses an operand: a bare literal (`10`, `0x1234`), a register (`r0`..`r7`), or a
+/// label reference resolved in the second pass. Labels are returned as `Operand::Label`;
+/// the caller records the reference in `fixups` so it can be patched once every label
+/// address is known.
+enum Operand {
+    Word(u16),
+    Label(String),
+}
+
+fn parse_operand(tok: &str) -> Operand {
+    if let Some(reg) = tok.strip_prefix('r') {
+        if let Ok(n) = reg.parse::<u16>() {
+            if n <= 7 {
+                return Operand::Word(32768 + n);
+            }
+        }
+    }
+    if let Some(hex) = tok.strip_prefix("0x") {
+        if let Ok(n) = u16::from_str_radix(hex, 16) {
+            return Operand::Word(n);
+        }
+    }
+    if let Ok(n) = tok.parse::<u16>() {
+        return Operand::Word(n);
+    }
+    Operand::Label(tok.to_string())
+}
+
+/// Assembles `src` into a word stream ready for `Machine::load`. Supports one
+/// instruction or directive per line, `name:` label definitions, and forward/backward
+/// label references (resolved to word addresses in a second pass once the whole program
+/// has been laid out).
+pub fn assemble(src: &str) -> Vec<u16> {
+    let mut words: Vec<u16> = Vec::new();
+    let mut labels: BTreeMap<String, u16> = BTreeMap::new();
+    let mut fixups: Vec<(usize, String)> = Vec::new();
+
+    for raw_line in src.lines() {
+        let line = strip_comment(raw_line).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_suffix(':') {
+            labels.insert(name.trim().to_string(), words.len() as u16);
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let head = tokens.next().unwrap();
+
+        if head == ".string" {
+            let rest = line[head.len()..].trim();
+            let literal = rest
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(rest);
+            words.extend(literal.chars().map(|c| c as u16));
+            continue;
+        }
+
+        if head == "data" {
+            let tok = tokens.next().expect("data directive requires a value");
+            match parse_operand(tok) {
+                Operand::Word(w) => words.push(w),
+                Operand::Label(name) => {
+                    fixups.push((words.len(), name));
+                    words.push(0);
+                }
+            }
+            continue;
+        }
+
+        let opcode = opcode_of(head).unwrap_or_else(|| panic!("unknown mnemonic: {head}"));
+        words.push(opcode);
+
+        let arity = arity_of(opcode);
+        for i in 0..arity {
+            let tok = tokens.next().unwrap_or_else(|| {
+                panic!("{head} requires {arity} operand(s), missing operand {}", i + 1)
+            });
+            match parse_operand(tok) {
+                Operand::Word(w) => words.push(w),
+                Operand::Label(name) => {
+                    fixups.push((words.len(), name));
+                    words.push(0);
+                }
+            }
+        }
+        if let Some(extra) = tokens.next() {
+            panic!("{head} takes {arity} operand(s), found extra token: {extra}");
+        }
+    }
+
+    for (pos, name) in fixups {
+        let addr = *labels
+            .get(&name)
+            .unwrap_or_else(|| panic!("undefined label: {name}"));
+        words[pos] = addr;
+    }
+
+    words
+}
+
+/// Truncates `line` at the first `;` that isn't inside a `"..."` span, so a `;` in a
+/// `.string` literal isn't mistaken for a comment marker.
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Encodes `words` as little-endian byte pairs, matching the format `Machine::load` reads.
+pub fn to_bytes(words: &[u16]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(words.len() * 2);
+    for &word in words {
+        bytes.push((word & 0xff) as u8);
+        bytes.push((word >> 8) as u8);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn assemble_disassemble_round_trip() {
+        let words = assemble("out 65\nhalt\n");
+        assert_eq!(words, vec![19, 65, 0]);
+
+        let mut machine: Machine = Machine::new();
+        machine.memory[..words.len()].copy_from_slice(&words);
+        let text = machine.disassemble(0..words.len() as u16);
+        assert!(text.contains("out"));
+        assert!(text.contains("halt"));
+    }
+
+    #[test]
+    fn labels_resolve_forward_and_backward() {
+        // word 0: jmp skip (opcode 6, operand is the fixup)
+        // word 2: halt
+        // word 3: skip: out 65
+        // word 5: jmp skip (backward reference, operand at word 6)
+        let words = assemble("jmp skip\nhalt\nskip:\nout 65\njmp skip\n");
+        assert_eq!(words[1], 3);
+        assert_eq!(words[6], 3);
+    }
+
+    #[test]
+    fn string_directive_ignores_semicolon_inside_quotes() {
+        let words = assemble(".string \"hi;there\"\n");
+        let decoded: String = words.iter().map(|&w| w as u8 as char).collect();
+        assert_eq!(decoded, "hi;there");
+    }
+
+    #[test]
+    #[should_panic(expected = "requires 3 operand")]
+    fn short_operand_list_is_rejected() {
+        assemble("add r0 1\n");
+    }
+}
@@ -0,0 +1,42 @@
+extern crate synacor_challenge;
+
+use self::synacor_challenge::debugger::Debugger;
+use self::synacor_challenge::Machine;
+use std::env;
+use std::io::{self, BufRead, Write};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        println!("Please provide an input path.");
+        return;
+    };
+
+    let mut machine: Machine = Machine::new();
+    let read = machine.load(&path).unwrap_or(0);
+    println!("Read {read} bytes.");
+    println!(
+        "commands: break <addr>, clear <addr>, reg rN[ = val], mem <start> <end>, stack, step, continue, quit"
+    );
+
+    let mut debugger = Debugger::new();
+    let stdin = io::stdin();
+
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
+
+        println!("{}", debugger.execute(&mut machine, line));
+    }
+}
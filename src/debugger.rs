@@ -0,0 +1,237 @@
+//! Interactive front-end around `Machine::tick`: breakpoints checked before each tick,
+//! register/memory/call-stack inspection, and single-stepping. Crucially, registers can
+//! be written mid-run, so a user can patch register 8 before the teleporter's
+//! confirmation check and skip straight past its expensive verification loop.
+
+use crate::io::Io;
+use crate::{Fault, Machine, TickOutcome, VmError};
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::collections::BTreeSet;
+#[cfg(not(feature = "std"))]
+use alloc::{collections::BTreeSet, format, string::String, string::ToString, vec::Vec};
+
+/// A point-in-time view of the machine, captured when a breakpoint fires so it can be
+/// inspected without racing the next `tick`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub pos: u16,
+    pub registers: Vec<u16>,
+    pub stack: Vec<u16>,
+}
+
+/// The outcome of running until something worth stopping for.
+#[derive(Debug)]
+pub enum RunResult {
+    Breakpoint(Snapshot),
+    Halted,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn set_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn clear_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    fn snapshot<IO: Io>(machine: &Machine<IO>) -> Snapshot {
+        Snapshot {
+            pos: machine.pos(),
+            registers: machine.registers().to_vec(),
+            stack: machine.stack().to_vec(),
+        }
+    }
+
+    /// Executes a single instruction regardless of breakpoints.
+    pub fn step<IO: Io>(&self, machine: &mut Machine<IO>) -> Result<TickOutcome, VmError> {
+        machine.tick()
+    }
+
+    /// Runs until a breakpoint is hit, the machine halts, or it faults. If `machine` is
+    /// already sitting on a breakpoint, that one instruction is stepped over first so
+    /// `continue` makes forward progress instead of re-triggering immediately.
+    pub fn cont<IO: Io>(&self, machine: &mut Machine<IO>) -> Result<RunResult, Fault> {
+        if self.breakpoints.contains(&machine.pos()) {
+            let pos = machine.pos();
+            match machine.tick() {
+                Ok(TickOutcome::Halt) => return Ok(RunResult::Halted),
+                Err(error) => return Err(Fault { error, pos }),
+                Ok(TickOutcome::Continue) => {}
+            }
+        }
+
+        loop {
+            if self.breakpoints.contains(&machine.pos()) {
+                return Ok(RunResult::Breakpoint(Self::snapshot(machine)));
+            }
+            let pos = machine.pos();
+            match machine.tick() {
+                Ok(TickOutcome::Continue) => continue,
+                Ok(TickOutcome::Halt) => return Ok(RunResult::Halted),
+                Err(error) => return Err(Fault { error, pos }),
+            }
+        }
+    }
+
+    pub fn read_register<IO: Io>(&self, machine: &Machine<IO>, index: u16) -> u16 {
+        machine.registers()[index as usize]
+    }
+
+    pub fn write_register<IO: Io>(
+        &self,
+        machine: &mut Machine<IO>,
+        index: u16,
+        val: u16,
+    ) -> Result<(), VmError> {
+        machine.write_register(32768 + index, val)
+    }
+
+    /// Returns `None` instead of panicking when `range` runs past the end of memory.
+    pub fn dump_memory<'a, IO: Io>(
+        &self,
+        machine: &'a Machine<IO>,
+        range: Range<u16>,
+    ) -> Option<&'a [u16]> {
+        machine
+            .memory()
+            .get(range.start as usize..range.end as usize)
+    }
+
+    pub fn call_stack<'a, IO: Io>(&self, machine: &'a Machine<IO>) -> &'a [u16] {
+        machine.stack()
+    }
+
+    /// Parses and runs one debugger command line, returning its textual result. Supports
+    /// `break <addr>`, `clear <addr>`, `reg rN`, `reg rN = val`, `mem <start> <end>`,
+    /// `stack`, `step`, and `continue`.
+    pub fn execute<IO: Io>(&mut self, machine: &mut Machine<IO>, line: &str) -> String {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("break") => match tokens.next().and_then(parse_addr) {
+                Some(addr) => {
+                    self.set_breakpoint(addr);
+                    format!("breakpoint set at {addr}")
+                }
+                None => "break requires an address".to_string(),
+            },
+            Some("clear") => match tokens.next().and_then(parse_addr) {
+                Some(addr) => {
+                    self.clear_breakpoint(addr);
+                    format!("breakpoint cleared at {addr}")
+                }
+                None => "clear requires an address".to_string(),
+            },
+            Some("reg") => {
+                let Some(index) = tokens.next().and_then(parse_register) else {
+                    return "reg requires a register name (r0..r7)".to_string();
+                };
+                match tokens.next() {
+                    Some("=") => match tokens.next().and_then(parse_addr) {
+                        Some(val) => match self.write_register(machine, index, val) {
+                            Ok(()) => format!("r{index} = {val}"),
+                            Err(error) => format!("fault: {error:?}"),
+                        },
+                        None => "reg assignment requires a value".to_string(),
+                    },
+                    None => format!("r{index} = {}", self.read_register(machine, index)),
+                    Some(other) => format!("unexpected token: {other}"),
+                }
+            }
+            Some("mem") => {
+                let bounds = tokens
+                    .next()
+                    .and_then(parse_addr)
+                    .zip(tokens.next().and_then(parse_addr));
+                match bounds {
+                    Some((start, end)) => match self.dump_memory(machine, start..end) {
+                        Some(words) => format!("{words:?}"),
+                        None => format!("address range {start}..{end} out of bounds"),
+                    },
+                    None => "mem requires a start and end address".to_string(),
+                }
+            }
+            Some("stack") => format!("{:?}", self.call_stack(machine)),
+            Some("step") => match self.step(machine) {
+                Ok(outcome) => format!("{outcome:?} at {}", machine.pos()),
+                Err(error) => format!("fault: {error:?}"),
+            },
+            Some("continue") => match self.cont(machine) {
+                Ok(RunResult::Breakpoint(snapshot)) => {
+                    format!("breakpoint hit at {}", snapshot.pos)
+                }
+                Ok(RunResult::Halted) => "halted".to_string(),
+                Err(fault) => format!("fault at {}: {:?}", fault.pos, fault.error),
+            },
+            Some(other) => format!("unknown command: {other}"),
+            None => String::new(),
+        }
+    }
+}
+
+fn parse_addr(tok: &str) -> Option<u16> {
+    if let Some(hex) = tok.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        tok.parse().ok()
+    }
+}
+
+fn parse_register(tok: &str) -> Option<u16> {
+    let idx: u16 = tok.strip_prefix('r')?.parse().ok()?;
+    (idx <= 7).then_some(idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    fn machine_with_program(words: &[u16]) -> Machine {
+        let mut machine = Machine::new();
+        machine.memory[..words.len()].copy_from_slice(words);
+        machine
+    }
+
+    #[test]
+    fn dump_memory_out_of_range_returns_none_instead_of_panicking() {
+        let machine = machine_with_program(&[0]);
+        let debugger = Debugger::new();
+        assert!(debugger.dump_memory(&machine, 40000..40005).is_none());
+        assert!(debugger.dump_memory(&machine, 0..1).is_some());
+    }
+
+    #[test]
+    fn breakpoint_stops_continue_before_executing_it() {
+        // pos 0: noop, pos 1: noop, pos 2: noop, pos 3: halt
+        let mut machine = machine_with_program(&[21, 21, 21, 0]);
+        let mut debugger = Debugger::new();
+        debugger.set_breakpoint(3);
+
+        match debugger.cont(&mut machine).unwrap() {
+            RunResult::Breakpoint(snapshot) => assert_eq!(snapshot.pos, 3),
+            RunResult::Halted => panic!("expected to stop at the breakpoint before halting"),
+        }
+
+        match debugger.cont(&mut machine).unwrap() {
+            RunResult::Halted => {}
+            RunResult::Breakpoint(_) => panic!("expected to run to completion"),
+        }
+    }
+}
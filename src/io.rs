@@ -0,0 +1,81 @@
+//! Pluggable I/O for `Out`/`In`, decoupled from the VM core so it can be embedded and
+//! tested without touching global stdio. This mirrors holey-bytes' split between the
+//! executor and its host environment.
+//!
+//! The VM core (this module, `Machine::tick`, the assembler, and the debugger) builds
+//! under `#![no_std]` when the `std` feature is disabled; only `StdIo` and
+//! `Machine::load` require `std` and are gated behind the `std` feature accordingly. A
+//! `Cargo.toml` declaring `std`/`alloc` features (with `std` on by default, matching
+//! `main.rs` and `src/bin/dbg.rs`) is needed to actually select between them; this tree
+//! doesn't ship one, so this is written as the feature split would look with one in place.
+
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(not(feature = "std"))]
+use alloc::{collections::VecDeque, vec::Vec};
+
+/// A byte sink/source for the `Out`/`In` opcodes.
+pub trait Io {
+    fn write_byte(&mut self, byte: u8);
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+/// The default `Io`, backed by real stdin/stdout. Requires the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+#[cfg(feature = "std")]
+impl Io for StdIo {
+    fn write_byte(&mut self, byte: u8) {
+        print!("{}", byte as char);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        use std::io::Read;
+        std::io::stdin().bytes().next().and_then(Result::ok)
+    }
+}
+
+/// An in-memory `Io` that feeds a scripted input buffer and captures everything written,
+/// so self-test runs and puzzle solutions can be driven deterministically in unit tests.
+#[derive(Debug, Default, Clone)]
+pub struct BufferIo {
+    input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl BufferIo {
+    pub fn new(input: impl Into<Vec<u8>>) -> BufferIo {
+        BufferIo {
+            input: input.into().into(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Io for BufferIo {
+    fn write_byte(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_io_round_trips_written_and_read_bytes() {
+        let mut io = BufferIo::new(vec![b'a', b'b']);
+        io.write_byte(b'x');
+        io.write_byte(b'y');
+        assert_eq!(io.output, vec![b'x', b'y']);
+        assert_eq!(io.read_byte(), Some(b'a'));
+        assert_eq!(io.read_byte(), Some(b'b'));
+        assert_eq!(io.read_byte(), None);
+    }
+}
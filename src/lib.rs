@@ -1,4 +1,18 @@
-use std::io::Read;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::String, string::ToString, vec, vec::Vec};
+
+pub mod assembler;
+pub mod debugger;
+pub mod io;
+
+use io::Io;
+#[cfg(feature = "std")]
+use io::StdIo;
 
 //  - an unbounded stack which holds individual 16-bit values
 //  - memory with 15-bit address space storing 16-bit values
@@ -28,88 +42,267 @@ enum OpCode {
     Out(u16),
     In(u16),
     Noop,
-    None,
 }
 
-pub struct Machine {
+/// A recoverable trap raised by the VM core. Callers decide how to surface it instead of
+/// the process aborting outright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmError {
+    InvalidOpcode(u16),
+    InvalidAddress(u16),
+    InvalidRegister(u16),
+    StackUnderflow,
+    Halted,
+    InvalidSnapshot,
+}
+
+/// What happened during a single `tick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickOutcome {
+    Continue,
+    Halt,
+}
+
+/// A `VmError` tagged with the instruction address it was raised at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub error: VmError,
+    pub pos: u16,
+}
+
+/// The machine's run state, mirroring the moa `Processor` lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Init,
+    Running,
+    Halted,
+}
+
+/// `save_state` blob format version. Bump this and branch in `load_state` when adding
+/// fields, so old snapshots stay loadable.
+const SNAPSHOT_VERSION: u8 = 1;
+
+fn write_u16_vec(buf: &mut Vec<u8>, words: &[u16]) {
+    buf.extend_from_slice(&(words.len() as u32).to_le_bytes());
+    for &word in words {
+        buf.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+fn read_u16(bytes: &[u8], cursor: &mut usize) -> Result<u16, VmError> {
+    let end = cursor.checked_add(2).ok_or(VmError::InvalidSnapshot)?;
+    let slice = bytes.get(*cursor..end).ok_or(VmError::InvalidSnapshot)?;
+    let val = u16::from_le_bytes(slice.try_into().unwrap());
+    *cursor = end;
+    Ok(val)
+}
+
+fn read_u32(bytes: &[u8], cursor: &mut usize) -> Result<u32, VmError> {
+    let end = cursor.checked_add(4).ok_or(VmError::InvalidSnapshot)?;
+    let slice = bytes.get(*cursor..end).ok_or(VmError::InvalidSnapshot)?;
+    let val = u32::from_le_bytes(slice.try_into().unwrap());
+    *cursor = end;
+    Ok(val)
+}
+
+fn read_u16_vec(bytes: &[u8], cursor: &mut usize) -> Result<Vec<u16>, VmError> {
+    let len = read_u32(bytes, cursor)? as usize;
+    (0..len).map(|_| read_u16(bytes, cursor)).collect()
+}
+
+/// The `Io` a bare `Machine<..>` defaults to when nothing else pins the type parameter.
+/// Real stdin/stdout under `std`; `BufferIo` otherwise, since `StdIo` itself requires `std`.
+#[cfg(feature = "std")]
+type DefaultIo = StdIo;
+#[cfg(not(feature = "std"))]
+type DefaultIo = io::BufferIo;
+
+pub struct Machine<IO: Io = DefaultIo> {
     memory: Vec<u16>,
     registers: Vec<u16>,
     stack: Vec<u16>,
     pos: u16,
+    state: State,
+    io: IO,
 }
 
-impl Default for Machine {
+impl<IO: Io + Default> Default for Machine<IO> {
     fn default() -> Self {
+        Machine::new()
+    }
+}
+
+impl<IO: Io + Default> Machine<IO> {
+    pub fn new() -> Machine<IO> {
         Machine {
-            memory: vec![0, 32768],
-            registers: vec![0, 8],
+            memory: vec![0; 32768],
+            registers: vec![0; 8],
             stack: vec![],
             pos: 0,
+            state: State::Init,
+            io: IO::default(),
         }
     }
 }
 
-impl Machine {
-    pub fn new() -> Machine {
+impl<IO: Io> Machine<IO> {
+    /// Builds a machine around a caller-supplied `Io`, e.g. a `BufferIo` for scripted,
+    /// deterministic test runs.
+    pub fn with_io(io: IO) -> Machine<IO> {
         Machine {
             memory: vec![0; 32768],
             registers: vec![0; 8],
             stack: vec![],
             pos: 0,
+            state: State::Init,
+            io,
+        }
+    }
+
+    /// Zeroes registers and the stack and rewinds `pos` to 0, without reloading memory.
+    pub fn reset(&mut self) {
+        self.registers = vec![0; self.registers.len()];
+        self.stack.clear();
+        self.pos = 0;
+        self.state = State::Init;
+    }
+
+    /// Serializes memory, registers, the stack, `pos`, and run state into a compact,
+    /// versioned little-endian blob, for snapshotting before an expensive or destructive
+    /// operation (e.g. brute-forcing the teleporter's confirmation register).
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buf = vec![SNAPSHOT_VERSION];
+        write_u16_vec(&mut buf, &self.memory);
+        write_u16_vec(&mut buf, &self.registers);
+        write_u16_vec(&mut buf, &self.stack);
+        buf.extend_from_slice(&self.pos.to_le_bytes());
+        buf.push(match self.state {
+            State::Init => 0,
+            State::Running => 1,
+            State::Halted => 2,
+        });
+        buf
+    }
+
+    /// Restores a snapshot produced by `save_state`, replacing memory, registers, the
+    /// stack, `pos`, and run state wholesale. Returns `Err(VmError::InvalidSnapshot)` on a
+    /// version mismatch or a truncated/corrupt buffer instead of panicking, since `bytes`
+    /// may come from an untrusted source (a file, a brute-force search checkpoint, etc.).
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        let mut cursor = 0;
+        let version = *bytes.get(cursor).ok_or(VmError::InvalidSnapshot)?;
+        cursor += 1;
+        if version != SNAPSHOT_VERSION {
+            return Err(VmError::InvalidSnapshot);
+        }
+
+        let memory = read_u16_vec(bytes, &mut cursor)?;
+        let registers = read_u16_vec(bytes, &mut cursor)?;
+        let stack = read_u16_vec(bytes, &mut cursor)?;
+        let pos = read_u16(bytes, &mut cursor)?;
+        let state = match *bytes.get(cursor).ok_or(VmError::InvalidSnapshot)? {
+            0 => State::Init,
+            1 => State::Running,
+            2 => State::Halted,
+            _ => return Err(VmError::InvalidSnapshot),
+        };
+
+        self.memory = memory;
+        self.registers = registers;
+        self.stack = stack;
+        self.pos = pos;
+        self.state = state;
+        Ok(())
+    }
+
+    fn register_index(reg: u16) -> Result<usize, VmError> {
+        if (32768..32776).contains(&reg) {
+            Ok((reg - 32768) as usize)
+        } else {
+            Err(VmError::InvalidRegister(reg))
         }
     }
 
-    fn get_register(&self, reg: u16) -> u16 {
-        self.registers[(reg - 32768) as usize]
+    fn get_register(&self, reg: u16) -> Result<u16, VmError> {
+        Ok(self.registers[Self::register_index(reg)?])
     }
 
-    fn set_register(&mut self, reg: u16, val: u16) {
-        self.registers[(reg - 32768) as usize] = val;
+    fn set_register(&mut self, reg: u16, val: u16) -> Result<(), VmError> {
+        self.registers[Self::register_index(reg)?] = val;
+        Ok(())
     }
 
-    fn value(&self, arg: u16) -> u16 {
+    pub fn pos(&self) -> u16 {
+        self.pos
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn registers(&self) -> &[u16] {
+        &self.registers
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &[u16] {
+        &self.memory
+    }
+
+    /// Writes `reg` (a raw register word, 32768..32776) directly, bypassing normal
+    /// execution. Used by the debugger to patch a register mid-run.
+    pub fn write_register(&mut self, reg: u16, val: u16) -> Result<(), VmError> {
+        self.set_register(reg, val)
+    }
+
+    fn value(&self, arg: u16) -> Result<u16, VmError> {
         if arg <= 32767 {
-            arg
-        } else if arg <= 32775 {
-            self.get_register(arg)
+            Ok(arg)
         } else {
-            panic!()
+            self.get_register(arg)
         }
     }
 
-    fn next(&mut self) -> u16 {
+    fn next(&mut self) -> Result<u16, VmError> {
         let pos = self.pos as usize;
-        let ret = self.memory[pos];
-        self.pos = (pos + 1) as u16;
-        ret
+        let ret = *self
+            .memory
+            .get(pos)
+            .ok_or(VmError::InvalidAddress(self.pos))?;
+        self.pos = self.pos.wrapping_add(1);
+        Ok(ret)
     }
 
-    fn match_opcode(&mut self) -> OpCode {
-        match self.next() {
+    fn match_opcode(&mut self) -> Result<OpCode, VmError> {
+        let word = self.next()?;
+        Ok(match word {
             0 => OpCode::Halt,
-            1 => OpCode::Set(self.next(), self.next()),
-            2 => OpCode::Push(self.next()),
-            3 => OpCode::Pop(self.next()),
-            4 => OpCode::Eq(self.next(), self.next(), self.next()),
-            5 => OpCode::Gt(self.next(), self.next(), self.next()),
-            6 => OpCode::Jmp(self.next()),
-            7 => OpCode::Jt(self.next(), self.next()),
-            8 => OpCode::Jf(self.next(), self.next()),
-            9 => OpCode::Add(self.next(), self.next(), self.next()),
-            10 => OpCode::Mult(self.next(), self.next(), self.next()),
-            11 => OpCode::Mod(self.next(), self.next(), self.next()),
-            12 => OpCode::And(self.next(), self.next(), self.next()),
-            13 => OpCode::Or(self.next(), self.next(), self.next()),
-            14 => OpCode::Not(self.next(), self.next()),
-            15 => OpCode::Rmem(self.next(), self.next()),
-            16 => OpCode::Wmem(self.next(), self.next()),
-            17 => OpCode::Call(self.next()),
+            1 => OpCode::Set(self.next()?, self.next()?),
+            2 => OpCode::Push(self.next()?),
+            3 => OpCode::Pop(self.next()?),
+            4 => OpCode::Eq(self.next()?, self.next()?, self.next()?),
+            5 => OpCode::Gt(self.next()?, self.next()?, self.next()?),
+            6 => OpCode::Jmp(self.next()?),
+            7 => OpCode::Jt(self.next()?, self.next()?),
+            8 => OpCode::Jf(self.next()?, self.next()?),
+            9 => OpCode::Add(self.next()?, self.next()?, self.next()?),
+            10 => OpCode::Mult(self.next()?, self.next()?, self.next()?),
+            11 => OpCode::Mod(self.next()?, self.next()?, self.next()?),
+            12 => OpCode::And(self.next()?, self.next()?, self.next()?),
+            13 => OpCode::Or(self.next()?, self.next()?, self.next()?),
+            14 => OpCode::Not(self.next()?, self.next()?),
+            15 => OpCode::Rmem(self.next()?, self.next()?),
+            16 => OpCode::Wmem(self.next()?, self.next()?),
+            17 => OpCode::Call(self.next()?),
             18 => OpCode::Ret,
-            19 => OpCode::Out(self.next()),
-            20 => OpCode::In(self.next()),
+            19 => OpCode::Out(self.next()?),
+            20 => OpCode::In(self.next()?),
             21 => OpCode::Noop,
-            _ => OpCode::None,
-        }
+            _ => return Err(VmError::InvalidOpcode(word)),
+        })
     }
 
     fn add(&self, a: u16, b: u16) -> u16 {
@@ -122,6 +315,9 @@ impl Machine {
         res as u16
     }
 
+    /// Loads a program from disk into memory. Requires the `std` feature since it goes
+    /// through `std::fs::File`; the VM core otherwise has no filesystem dependency.
+    #[cfg(feature = "std")]
     pub fn load(&mut self, path: &str) -> std::io::Result<u16> {
         use std::fs::File;
         use std::io::prelude::*;
@@ -139,74 +335,263 @@ impl Machine {
         Ok(read.try_into().unwrap())
     }
 
-    pub fn tick(&mut self) -> bool {
-        match self.match_opcode() {
-            OpCode::Halt => {
-                return false;
+    /// Render `range` as Synacor assembly, one `address: mnemonic args` line per
+    /// instruction. Words that aren't a valid opcode are emitted as a `data 0xXXXX`
+    /// pseudo-op and the cursor only advances by one word, so a stray literal in the
+    /// middle of code doesn't desync the rest of the decode.
+    pub fn disassemble(&self, range: core::ops::Range<u16>) -> String {
+        let mut out = String::new();
+        let mut addr = range.start as usize;
+        let end = range.end as usize;
+
+        while addr < end && addr < self.memory.len() {
+            let opcode = self.memory[addr];
+            let (mnemonic, arity) = match opcode {
+                0 => ("halt", 0),
+                1 => ("set", 2),
+                2 => ("push", 1),
+                3 => ("pop", 1),
+                4 => ("eq", 3),
+                5 => ("gt", 3),
+                6 => ("jmp", 1),
+                7 => ("jt", 2),
+                8 => ("jf", 2),
+                9 => ("add", 3),
+                10 => ("mult", 3),
+                11 => ("mod", 3),
+                12 => ("and", 3),
+                13 => ("or", 3),
+                14 => ("not", 2),
+                15 => ("rmem", 2),
+                16 => ("wmem", 2),
+                17 => ("call", 1),
+                18 => ("ret", 0),
+                19 => ("out", 1),
+                20 => ("in", 1),
+                21 => ("noop", 0),
+                _ => {
+                    out.push_str(&format!("{addr}: data {opcode:#06x}\n"));
+                    addr += 1;
+                    continue;
+                }
+            };
+
+            let args: Vec<u16> = (0..arity)
+                .map(|i| self.memory.get(addr + 1 + i).copied().unwrap_or(0))
+                .collect();
+
+            out.push_str(&format!("{addr}: {mnemonic}"));
+            for &arg in &args {
+                out.push(' ');
+                out.push_str(&Self::format_arg(arg));
             }
+            if mnemonic == "out" && args[0] <= 32767 {
+                let ch = (args[0] as u8) as char;
+                out.push_str(&format!(" ; '{}'", ch.escape_default()));
+            }
+            out.push('\n');
+
+            addr += 1 + arity;
+        }
+
+        out
+    }
+
+    fn format_arg(arg: u16) -> String {
+        if arg <= 32767 {
+            arg.to_string()
+        } else if arg <= 32775 {
+            format!("r{}", arg - 32768)
+        } else {
+            format!("{arg:#06x}")
+        }
+    }
+
+    pub fn tick(&mut self) -> Result<TickOutcome, VmError> {
+        match self.match_opcode()? {
+            OpCode::Halt => return Ok(TickOutcome::Halt),
             OpCode::Set(a, b) => {
-                let val = self.value(b);
-                self.set_register(a, val);
+                let val = self.value(b)?;
+                self.set_register(a, val)?;
             }
             OpCode::Push(a) => {
-                let val = self.value(a);
+                let val = self.value(a)?;
                 self.stack.push(val);
             }
             OpCode::Pop(a) => {
-                let top = self.stack.pop();
-                match top {
-                    Some(top_value) => self.set_register(a, top),
-                    None => return false,
-                }
+                let top = self.stack.pop().ok_or(VmError::StackUnderflow)?;
+                self.set_register(a, top)?;
             }
             OpCode::Eq(a, b, c) => {
-                self.set_register(a, (self.value(b) == self.value(c)).into());
+                let val = self.value(b)? == self.value(c)?;
+                self.set_register(a, val.into())?;
             }
             OpCode::Gt(a, b, c) => {
-                self.set_register(a, (self.value(b) > self.value(c)).into());
+                let val = self.value(b)? > self.value(c)?;
+                self.set_register(a, val.into())?;
             }
-            OpCode::Jmp(a) => self.pos = self.value(a),
+            OpCode::Jmp(a) => self.pos = self.value(a)?,
             OpCode::Jt(a, b) => {
-                if self.value(a) != 0 {
-                    self.pos = self.value(b)
+                if self.value(a)? != 0 {
+                    self.pos = self.value(b)?
                 }
             }
             OpCode::Jf(a, b) => {
-                if self.value(a) == 0 {
-                    self.pos = self.value(b)
+                if self.value(a)? == 0 {
+                    self.pos = self.value(b)?
                 }
             }
-            OpCode::Add(a, b, c) => self.set_register(a, self.add(self.value(b), self.value(c))),
-            OpCode::Mult(a, b, c) => self.set_register(a, self.mult(self.value(b), self.value(c))),
-            OpCode::Mod(a, b, c) => self.set_register(a, self.value(b) % self.value(c)),
-            OpCode::And(a, b, c) => self.set_register(a, self.value(b) & self.value(c)),
-            OpCode::Or(a, b, c) => self.set_register(a, self.value(b) | self.value(c)),
-            OpCode::Not(a, b) => self.set_register(a, (!self.value(b)) & 32767),
-            OpCode::Rmem(a, b) => self.set_register(a, self.memory[self.value(b) as usize]),
+            OpCode::Add(a, b, c) => {
+                let val = self.add(self.value(b)?, self.value(c)?);
+                self.set_register(a, val)?;
+            }
+            OpCode::Mult(a, b, c) => {
+                let val = self.mult(self.value(b)?, self.value(c)?);
+                self.set_register(a, val)?;
+            }
+            OpCode::Mod(a, b, c) => {
+                let val = self.value(b)? % self.value(c)?;
+                self.set_register(a, val)?;
+            }
+            OpCode::And(a, b, c) => {
+                let val = self.value(b)? & self.value(c)?;
+                self.set_register(a, val)?;
+            }
+            OpCode::Or(a, b, c) => {
+                let val = self.value(b)? | self.value(c)?;
+                self.set_register(a, val)?;
+            }
+            OpCode::Not(a, b) => {
+                let val = (!self.value(b)?) & 32767;
+                self.set_register(a, val)?;
+            }
+            OpCode::Rmem(a, b) => {
+                let addr = self.value(b)?;
+                let val = *self
+                    .memory
+                    .get(addr as usize)
+                    .ok_or(VmError::InvalidAddress(addr))?;
+                self.set_register(a, val)?;
+            }
             OpCode::Wmem(a, b) => {
-                let address = self.value(a) as usize;
-                self.memory[address] = self.value(b)
+                let addr = self.value(a)?;
+                let val = self.value(b)?;
+                let slot = self
+                    .memory
+                    .get_mut(addr as usize)
+                    .ok_or(VmError::InvalidAddress(addr))?;
+                *slot = val;
             }
             OpCode::Call(a) => {
+                let target = self.value(a)?;
                 self.stack.push(self.pos);
-                self.pos = self.value(a);
+                self.pos = target;
+            }
+            OpCode::Ret => {
+                self.pos = self.stack.pop().ok_or(VmError::StackUnderflow)?;
+            }
+            OpCode::Out(a) => {
+                let val = self.value(a)?;
+                self.io.write_byte(val as u8);
             }
-            OpCode::Ret => match self.stack.pop() {
-                Some(val) => self.pos = val,
-                None => return false,
-            },
-            OpCode::Out(a) => print!("{}", (self.value(a) as u8) as char),
             OpCode::In(a) => {
-                let c: u8 = std::io::stdin().bytes().nth(0).expect("EOF").expect("EOF");
-                self.set_register(a, c as u16);
+                let byte = self.io.read_byte().ok_or(VmError::Halted)?;
+                self.set_register(a, byte as u16)?;
             }
             OpCode::Noop => (),
+        }
+        Ok(TickOutcome::Continue)
+    }
+
+    pub fn exec(&mut self) -> Result<(), Fault> {
+        loop {
+            let pos = self.pos;
+            match self.tick() {
+                Ok(TickOutcome::Continue) => self.state = State::Running,
+                Ok(TickOutcome::Halt) => {
+                    self.state = State::Halted;
+                    return Ok(());
+                }
+                Err(error) => return Err(Fault { error, pos }),
+            }
+        }
+    }
 
-            _ => panic!(),
+    /// Runs at most `cycles` instructions, returning how many actually ran and the
+    /// resulting `State`. Lets a host cap a runaway loop instead of running to completion.
+    pub fn run_for(&mut self, cycles: u64) -> Result<(u64, State), Fault> {
+        for ran in 0..cycles {
+            let pos = self.pos;
+            match self.tick() {
+                Ok(TickOutcome::Continue) => self.state = State::Running,
+                Ok(TickOutcome::Halt) => {
+                    self.state = State::Halted;
+                    return Ok((ran + 1, self.state));
+                }
+                Err(error) => return Err(Fault { error, pos }),
+            }
         }
-        true
+        Ok((cycles, self.state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::BufferIo;
+
+    fn machine_with_program(words: &[u16]) -> Machine {
+        let mut machine = Machine::new();
+        machine.memory[..words.len()].copy_from_slice(words);
+        machine
     }
-    pub fn exec(&mut self) {
-        while self.tick() {}
+
+    #[test]
+    fn pop_on_empty_stack_is_a_stack_underflow() {
+        let mut machine = machine_with_program(&[3, 32768]); // pop r0
+        assert_eq!(machine.tick(), Err(VmError::StackUnderflow));
+    }
+
+    #[test]
+    fn ret_on_empty_stack_is_a_stack_underflow() {
+        let mut machine = machine_with_program(&[18]); // ret
+        assert_eq!(machine.tick(), Err(VmError::StackUnderflow));
+    }
+
+    #[test]
+    fn unknown_opcode_is_an_invalid_opcode_trap() {
+        let mut machine = machine_with_program(&[22]);
+        assert_eq!(machine.tick(), Err(VmError::InvalidOpcode(22)));
+    }
+
+    #[test]
+    fn out_of_range_register_is_an_invalid_register_trap() {
+        let mut machine = machine_with_program(&[1, 40000, 1]); // set 40000 1
+        assert_eq!(machine.tick(), Err(VmError::InvalidRegister(40000)));
+    }
+
+    #[test]
+    fn save_state_load_state_round_trips() {
+        let mut machine = machine_with_program(&[9, 32768, 1, 2]); // add r0 1 2
+        machine.tick().unwrap();
+        let snapshot = machine.save_state();
+
+        let mut restored: Machine = Machine::new();
+        restored.load_state(&snapshot).unwrap();
+
+        assert_eq!(restored.memory, machine.memory);
+        assert_eq!(restored.registers, machine.registers);
+        assert_eq!(restored.stack, machine.stack);
+        assert_eq!(restored.pos, machine.pos);
+        assert_eq!(restored.state, machine.state);
+    }
+
+    #[test]
+    fn buffer_io_captures_out_and_feeds_in() {
+        // out 72 ('H'), in r0, out r0, halt
+        let mut machine: Machine<BufferIo> = Machine::with_io(BufferIo::new(vec![b'i']));
+        machine.memory[..7].copy_from_slice(&[19, 72, 20, 32768, 19, 32768, 0]);
+        machine.exec().unwrap();
+        assert_eq!(machine.io.output, vec![b'H', b'i']);
     }
 }
@@ -11,7 +11,9 @@ fn main() {
             let read = machine.load(&path).unwrap_or(0);
             println!("Read {} bytes, executing.", read);
             println!("=========================");
-            machine.exec();
+            if let Err(fault) = machine.exec() {
+                eprintln!("vm fault at {}: {:?}", fault.pos, fault.error);
+            }
         }
     }
 }